@@ -38,6 +38,7 @@
 compile_error!("feature \"tokio\", \"async-std\" and \"blocking\" are mutually exclusive");
 
 use std::future::Future;
+use std::time::Duration;
 
 #[cfg(windows)]
 mod windows;
@@ -58,24 +59,26 @@ pub trait AsyncLockFileExt: AsDescriptor {
 	/// Asynchronously wait to obtain an exclusive lock
 	fn lock_exclusive(self) -> impl Future<Output = std::io::Result<Lock<Self>>> + Send where Self: Sized + 'static;
 
-	/// Try to obtain a shared lock
-	fn try_lock_shared(self) -> std::io::Result<LockResult<Self>> where Self: Sized + 'static {
-		try_lock_shared(self.as_descriptor())
-			.map(|locked| if locked {
-				LockResult::Locked(Lock::new(self))
-			} else {
-				LockResult::Blocking(self)
-			})
+	/// Try to obtain a shared lock.
+	///
+	/// On a genuine I/O error (as opposed to the lock merely being held elsewhere), `self` is
+	/// handed back alongside the error instead of being dropped, so the caller doesn't lose the
+	/// file over e.g. a stray `EINTR`.
+	fn try_lock_shared(self) -> Result<LockResult<Self>, (Self, std::io::Error)> where Self: Sized + 'static {
+		match try_lock_shared(AsDescriptor::as_descriptor(&self)) {
+			Ok(true) => Ok(LockResult::Locked(Lock::new(self))),
+			Ok(false) => Ok(LockResult::Blocking(self)),
+			Err(e) => Err((self, e)),
+		}
 	}
 
-	/// Try to obtain an exclusive lock
-	fn try_lock_exclusive(self) -> std::io::Result<LockResult<Self>> where Self: Sized + 'static {
-		try_lock_exclusive(self.as_descriptor())
-			.map(|locked| if locked {
-				LockResult::Locked(Lock::new(self))
-			} else {
-				LockResult::Blocking(self)
-			})
+	/// Try to obtain an exclusive lock, see [try_lock_shared](AsyncLockFileExt::try_lock_shared).
+	fn try_lock_exclusive(self) -> Result<LockResult<Self>, (Self, std::io::Error)> where Self: Sized + 'static {
+		match try_lock_exclusive(AsDescriptor::as_descriptor(&self)) {
+			Ok(true) => Ok(LockResult::Locked(Lock::new(self))),
+			Ok(false) => Ok(LockResult::Blocking(self)),
+			Err(e) => Err((self, e)),
+		}
 	}
 
 
@@ -87,15 +90,166 @@ pub trait AsyncLockFileExt: AsDescriptor {
 
 	/// Try to obtain a shared lock
 	fn try_lock_shared_ref<'a>(&'a mut self) -> std::io::Result<Option<LockRef<'a, Self>>> where Self: Sized + 'static {
-		try_lock_shared(self.as_descriptor())
+		try_lock_shared(AsDescriptor::as_descriptor(self))
 			.map(|locked| locked.then(|| LockRef::new(self)))
 	}
 
 	/// Try to obtain an exclusive lock
 	fn try_lock_exclusive_ref<'a>(&'a mut self) -> std::io::Result<Option<LockRef<'a, Self>>> where Self: Sized + 'static {
-		try_lock_exclusive(self.as_descriptor())
+		try_lock_exclusive(AsDescriptor::as_descriptor(self))
 			.map(|locked| locked.then(|| LockRef::new(self)))
 	}
+
+
+	/// Asynchronously wait to obtain a shared lock on the byte range `offset..offset + len`.
+	///
+	/// `len` must be non-zero -- unlike unix `fcntl`, this never means "to EOF".
+	///
+	/// Unlike the whole-file `flock`-backed shared lock, this requires `self` to be open for
+	/// reading on unix: a shared (`F_RDLCK`) `fcntl` request on a write-only descriptor fails
+	/// with `EBADF`.
+	fn lock_shared_range(self, offset: u64, len: u64) -> impl Future<Output = std::io::Result<Lock<Self>>> + Send where Self: Sized + 'static;
+
+	/// Asynchronously wait to obtain an exclusive lock on the byte range `offset..offset + len`
+	fn lock_exclusive_range(self, offset: u64, len: u64) -> impl Future<Output = std::io::Result<Lock<Self>>> + Send where Self: Sized + 'static;
+
+	/// Try to obtain a shared lock on the byte range `offset..offset + len`.
+	///
+	/// See [try_lock_shared](AsyncLockFileExt::try_lock_shared) for the I/O failure semantics.
+	fn try_lock_shared_range(self, offset: u64, len: u64) -> Result<LockResult<Self>, (Self, std::io::Error)> where Self: Sized + 'static {
+		match try_lock_shared_range(AsDescriptor::as_descriptor(&self), offset, len) {
+			Ok(true) => Ok(LockResult::Locked(Lock::new_range(self, offset, len))),
+			Ok(false) => Ok(LockResult::Blocking(self)),
+			Err(e) => Err((self, e)),
+		}
+	}
+
+	/// Try to obtain an exclusive lock on the byte range `offset..offset + len`.
+	///
+	/// See [try_lock_shared](AsyncLockFileExt::try_lock_shared) for the I/O failure semantics.
+	fn try_lock_exclusive_range(self, offset: u64, len: u64) -> Result<LockResult<Self>, (Self, std::io::Error)> where Self: Sized + 'static {
+		match try_lock_exclusive_range(AsDescriptor::as_descriptor(&self), offset, len) {
+			Ok(true) => Ok(LockResult::Locked(Lock::new_range(self, offset, len))),
+			Ok(false) => Ok(LockResult::Blocking(self)),
+			Err(e) => Err((self, e)),
+		}
+	}
+
+
+	/// Asynchronously wait to obtain a shared lock on the byte range `offset..offset + len`
+	fn lock_shared_range_ref(&mut self, offset: u64, len: u64) -> impl Future<Output = std::io::Result<LockRef<Self>>> + Send where Self: Sized + 'static;
+
+	/// Asynchronously wait to obtain an exclusive lock on the byte range `offset..offset + len`
+	fn lock_exclusive_range_ref(&mut self, offset: u64, len: u64) -> impl Future<Output = std::io::Result<LockRef<Self>>> + Send where Self: Sized + 'static;
+
+	/// Try to obtain a shared lock on the byte range `offset..offset + len`
+	fn try_lock_shared_range_ref<'a>(&'a mut self, offset: u64, len: u64) -> std::io::Result<Option<LockRef<'a, Self>>> where Self: Sized + 'static {
+		try_lock_shared_range(AsDescriptor::as_descriptor(self), offset, len)
+			.map(|locked| locked.then(|| LockRef::new_range(self, offset, len)))
+	}
+
+	/// Try to obtain an exclusive lock on the byte range `offset..offset + len`
+	fn try_lock_exclusive_range_ref<'a>(&'a mut self, offset: u64, len: u64) -> std::io::Result<Option<LockRef<'a, Self>>> where Self: Sized + 'static {
+		try_lock_exclusive_range(AsDescriptor::as_descriptor(self), offset, len)
+			.map(|locked| locked.then(|| LockRef::new_range(self, offset, len)))
+	}
+
+
+	/// Asynchronously wait to obtain a shared lock using the NFS-safe POSIX (`fcntl`) backend.
+	///
+	/// On unix, unlike the regular `flock`-backed locks, this is owned by the process rather
+	/// than the open file description, so it is released on *any* close of the file by this
+	/// process, not just when this descriptor is closed. On windows this is the same
+	/// `LockFileEx` lock as [lock_shared](AsyncLockFileExt::lock_shared).
+	///
+	/// This also requires `self` to be open for reading on unix, see [lock_shared_range](AsyncLockFileExt::lock_shared_range).
+	fn lock_shared_posix(self) -> impl Future<Output = std::io::Result<Lock<Self>>> + Send where Self: Sized + 'static;
+
+	/// Asynchronously wait to obtain an exclusive lock using the NFS-safe POSIX (`fcntl`) backend.
+	///
+	/// On unix, unlike the regular `flock`-backed locks, this is owned by the process rather
+	/// than the open file description, so it is released on *any* close of the file by this
+	/// process, not just when this descriptor is closed. On windows this is the same
+	/// `LockFileEx` lock as [lock_exclusive](AsyncLockFileExt::lock_exclusive).
+	fn lock_exclusive_posix(self) -> impl Future<Output = std::io::Result<Lock<Self>>> + Send where Self: Sized + 'static;
+
+	/// Try to obtain a shared lock using the NFS-safe POSIX (`fcntl`) backend.
+	///
+	/// See [try_lock_shared](AsyncLockFileExt::try_lock_shared) for the I/O failure semantics.
+	fn try_lock_shared_posix(self) -> Result<LockResult<Self>, (Self, std::io::Error)> where Self: Sized + 'static {
+		match try_lock_shared_posix(AsDescriptor::as_descriptor(&self)) {
+			Ok(true) => Ok(LockResult::Locked(Lock::new_posix(self))),
+			Ok(false) => Ok(LockResult::Blocking(self)),
+			Err(e) => Err((self, e)),
+		}
+	}
+
+	/// Try to obtain an exclusive lock using the NFS-safe POSIX (`fcntl`) backend.
+	///
+	/// See [try_lock_shared](AsyncLockFileExt::try_lock_shared) for the I/O failure semantics.
+	fn try_lock_exclusive_posix(self) -> Result<LockResult<Self>, (Self, std::io::Error)> where Self: Sized + 'static {
+		match try_lock_exclusive_posix(AsDescriptor::as_descriptor(&self)) {
+			Ok(true) => Ok(LockResult::Locked(Lock::new_posix(self))),
+			Ok(false) => Ok(LockResult::Blocking(self)),
+			Err(e) => Err((self, e)),
+		}
+	}
+
+
+	/// Asynchronously wait to obtain a shared lock using the NFS-safe POSIX (`fcntl`) backend
+	fn lock_shared_posix_ref(&mut self) -> impl Future<Output = std::io::Result<LockRef<Self>>> + Send where Self: Sized + 'static;
+
+	/// Asynchronously wait to obtain an exclusive lock using the NFS-safe POSIX (`fcntl`) backend
+	fn lock_exclusive_posix_ref(&mut self) -> impl Future<Output = std::io::Result<LockRef<Self>>> + Send where Self: Sized + 'static;
+
+	/// Try to obtain a shared lock using the NFS-safe POSIX (`fcntl`) backend
+	fn try_lock_shared_posix_ref<'a>(&'a mut self) -> std::io::Result<Option<LockRef<'a, Self>>> where Self: Sized + 'static {
+		try_lock_shared_posix(AsDescriptor::as_descriptor(self))
+			.map(|locked| locked.then(|| LockRef::new_posix(self)))
+	}
+
+	/// Try to obtain an exclusive lock using the NFS-safe POSIX (`fcntl`) backend
+	fn try_lock_exclusive_posix_ref<'a>(&'a mut self) -> std::io::Result<Option<LockRef<'a, Self>>> where Self: Sized + 'static {
+		try_lock_exclusive_posix(AsDescriptor::as_descriptor(self))
+			.map(|locked| locked.then(|| LockRef::new_posix(self)))
+	}
+
+
+	/// Wait up to `timeout` to obtain a shared lock.
+	///
+	/// Unlike [lock_shared](AsyncLockFileExt::lock_shared), this doesn't park a blocking-pool
+	/// thread for the whole wait: it polls the non-blocking backend with a capped exponential
+	/// backoff, so dropping the returned future abandons the wait immediately instead of leaving
+	/// a thread stuck inside a blocking `flock`/`LockFileEx` call. Fails with
+	/// [ErrorKind::TimedOut](std::io::ErrorKind::TimedOut) if the lock isn't obtained in time.
+	///
+	/// With the `blocking` feature, the sleep between polls is itself `blocking::unblock`-ed
+	/// onto a pool thread, which isn't reclaimed by dropping the future -- so on that backend an
+	/// abandoned wait still keeps a pool thread busy for up to the `POLL_BACKOFF_MAX` = 50ms the
+	/// sleep has left to run.
+	///
+	/// This polls on every platform, including Windows, rather than using overlapped
+	/// `LockFileEx` with `CancelIoEx`: the poll loop is already drop-cancellable and it keeps
+	/// `lock_poll`/`lock_poll_ref` -- and their `Descriptor` lifetime handling -- identical
+	/// across platforms instead of adding a second, overlapped-I/O-only code path.
+	fn lock_shared_timeout(self, timeout: Duration) -> impl Future<Output = std::io::Result<Lock<Self>>> + Send where Self: Sized + 'static {
+		lock_poll(self, LockSpan::Whole, true, timeout)
+	}
+
+	/// Wait up to `timeout` to obtain an exclusive lock, see [lock_shared_timeout](AsyncLockFileExt::lock_shared_timeout).
+	fn lock_exclusive_timeout(self, timeout: Duration) -> impl Future<Output = std::io::Result<Lock<Self>>> + Send where Self: Sized + 'static {
+		lock_poll(self, LockSpan::Whole, false, timeout)
+	}
+
+	/// Wait up to `timeout` to obtain a shared lock, see [lock_shared_timeout](AsyncLockFileExt::lock_shared_timeout).
+	fn lock_shared_timeout_ref<'a>(&'a mut self, timeout: Duration) -> impl Future<Output = std::io::Result<LockRef<'a, Self>>> + Send where Self: Sized + 'static {
+		lock_poll_ref(self, LockSpan::Whole, true, timeout)
+	}
+
+	/// Wait up to `timeout` to obtain an exclusive lock, see [lock_shared_timeout](AsyncLockFileExt::lock_shared_timeout).
+	fn lock_exclusive_timeout_ref<'a>(&'a mut self, timeout: Duration) -> impl Future<Output = std::io::Result<LockRef<'a, Self>>> + Send where Self: Sized + 'static {
+		lock_poll_ref(self, LockSpan::Whole, false, timeout)
+	}
 }
 
 
@@ -110,19 +264,73 @@ impl<T: AsDescriptor> AsyncLockFileExt for T {
 			#[cfg(feature = "blocking")]
 			let spawn = blocking::unblock;
 
-			let desc = self.as_descriptor();
+			// `self` is `Send + 'static`, so it's moved into the blocking task wholesale
+			// rather than splitting off a `Descriptor` that can't outlive it.
+			let res = spawn(move || {
+				let res = lock_shared(AsDescriptor::as_descriptor(&self));
+				(self, res)
+			}).await;
+
+			#[cfg(feature = "tokio")]
+			let (file, res) = res.unwrap();
+			#[cfg(not(feature = "tokio"))]
+			let (file, res) = res;
+
+			res.map(|_| Lock::new(file))
+		}
+	}
+
+	fn lock_exclusive(self) -> impl Future<Output = std::io::Result<Lock<Self>>> + Send {
+		async move {
+			#[cfg(feature = "tokio")]
+			let spawn = tokio::task::spawn_blocking;
+			#[cfg(feature = "async-std")]
+			let spawn = async_std::task::spawn_blocking;
+			#[cfg(feature = "blocking")]
+			let spawn = blocking::unblock;
+
+			let res = spawn(move || {
+				let res = lock_exclusive(AsDescriptor::as_descriptor(&self));
+				(self, res)
+			}).await;
+
+			#[cfg(feature = "tokio")]
+			let (file, res) = res.unwrap();
+			#[cfg(not(feature = "tokio"))]
+			let (file, res) = res;
+
+			res.map(|_| Lock::new(file))
+		}
+	}
 
-			let res = spawn(move || lock_shared(desc))
+
+	fn lock_shared_ref(&mut self) -> impl Future<Output = std::io::Result<LockRef<Self>>> + Send {
+		async move {
+			#[cfg(feature = "tokio")]
+			let spawn = tokio::task::spawn_blocking;
+			#[cfg(feature = "async-std")]
+			let spawn = async_std::task::spawn_blocking;
+			#[cfg(feature = "blocking")]
+			let spawn = blocking::unblock;
+
+			// `&mut self` can't be sent to the blocking task as a `Descriptor<'_>`, since that
+			// lifetime isn't `'static`. The raw value is `Copy`, so it crosses instead, and is
+			// turned back into a `Descriptor` on the other side.
+			//
+			// SAFETY: this future holds `&mut self` and doesn't return until the spawned task
+			// does, so the descriptor stays open for the entire borrow reconstructed below.
+			let raw = as_raw(AsDescriptor::as_descriptor(self));
+			let res = spawn(move || lock_shared(unsafe { from_raw(raw) }))
 				.await;
 
 			#[cfg(feature = "tokio")]
 			let res = res.unwrap();
 
-			res.map(|_| Lock::new(self))
+			res.map(|_| LockRef::new(self))
 		}
 	}
 
-	fn lock_exclusive(self) -> impl Future<Output = std::io::Result<Lock<Self>>> + Send {
+	fn lock_exclusive_ref(&mut self) -> impl Future<Output = std::io::Result<LockRef<Self>>> + Send {
 		async move {
 			#[cfg(feature = "tokio")]
 			let spawn = tokio::task::spawn_blocking;
@@ -131,20 +339,20 @@ impl<T: AsDescriptor> AsyncLockFileExt for T {
 			#[cfg(feature = "blocking")]
 			let spawn = blocking::unblock;
 
-			let desc = self.as_descriptor();
-
-			let res = spawn(move || lock_exclusive(desc))
+			// SAFETY: see `lock_shared_ref`
+			let raw = as_raw(AsDescriptor::as_descriptor(self));
+			let res = spawn(move || lock_exclusive(unsafe { from_raw(raw) }))
 				.await;
 
 			#[cfg(feature = "tokio")]
 			let res = res.unwrap();
 
-			res.map(|_| Lock::new(self))
+			res.map(|_| LockRef::new(self))
 		}
 	}
 
 
-	fn lock_shared_ref(&mut self) -> impl Future<Output = std::io::Result<LockRef<Self>>> + Send {
+	fn lock_shared_range(self, offset: u64, len: u64) -> impl Future<Output = std::io::Result<Lock<Self>>> + Send {
 		async move {
 			#[cfg(feature = "tokio")]
 			let spawn = tokio::task::spawn_blocking;
@@ -153,19 +361,111 @@ impl<T: AsDescriptor> AsyncLockFileExt for T {
 			#[cfg(feature = "blocking")]
 			let spawn = blocking::unblock;
 
-			let desc = self.as_descriptor();
+			let res = spawn(move || {
+				let res = lock_shared_range(AsDescriptor::as_descriptor(&self), offset, len);
+				(self, res)
+			}).await;
 
-			let res = spawn(move || lock_shared(desc))
+			#[cfg(feature = "tokio")]
+			let (file, res) = res.unwrap();
+			#[cfg(not(feature = "tokio"))]
+			let (file, res) = res;
+
+			res.map(|_| Lock::new_range(file, offset, len))
+		}
+	}
+
+	fn lock_exclusive_range(self, offset: u64, len: u64) -> impl Future<Output = std::io::Result<Lock<Self>>> + Send {
+		async move {
+			#[cfg(feature = "tokio")]
+			let spawn = tokio::task::spawn_blocking;
+			#[cfg(feature = "async-std")]
+			let spawn = async_std::task::spawn_blocking;
+			#[cfg(feature = "blocking")]
+			let spawn = blocking::unblock;
+
+			let res = spawn(move || {
+				let res = lock_exclusive_range(AsDescriptor::as_descriptor(&self), offset, len);
+				(self, res)
+			}).await;
+
+			#[cfg(feature = "tokio")]
+			let (file, res) = res.unwrap();
+			#[cfg(not(feature = "tokio"))]
+			let (file, res) = res;
+
+			res.map(|_| Lock::new_range(file, offset, len))
+		}
+	}
+
+
+	fn lock_shared_range_ref(&mut self, offset: u64, len: u64) -> impl Future<Output = std::io::Result<LockRef<Self>>> + Send {
+		async move {
+			#[cfg(feature = "tokio")]
+			let spawn = tokio::task::spawn_blocking;
+			#[cfg(feature = "async-std")]
+			let spawn = async_std::task::spawn_blocking;
+			#[cfg(feature = "blocking")]
+			let spawn = blocking::unblock;
+
+			// SAFETY: see `lock_shared_ref`
+			let raw = as_raw(AsDescriptor::as_descriptor(self));
+			let res = spawn(move || lock_shared_range(unsafe { from_raw(raw) }, offset, len))
 				.await;
 
 			#[cfg(feature = "tokio")]
 			let res = res.unwrap();
 
-			res.map(|_| LockRef::new(self))
+			res.map(|_| LockRef::new_range(self, offset, len))
 		}
 	}
 
-	fn lock_exclusive_ref(&mut self) -> impl Future<Output = std::io::Result<LockRef<Self>>> + Send {
+	fn lock_exclusive_range_ref(&mut self, offset: u64, len: u64) -> impl Future<Output = std::io::Result<LockRef<Self>>> + Send {
+		async move {
+			#[cfg(feature = "tokio")]
+			let spawn = tokio::task::spawn_blocking;
+			#[cfg(feature = "async-std")]
+			let spawn = async_std::task::spawn_blocking;
+			#[cfg(feature = "blocking")]
+			let spawn = blocking::unblock;
+
+			// SAFETY: see `lock_shared_ref`
+			let raw = as_raw(AsDescriptor::as_descriptor(self));
+			let res = spawn(move || lock_exclusive_range(unsafe { from_raw(raw) }, offset, len))
+				.await;
+
+			#[cfg(feature = "tokio")]
+			let res = res.unwrap();
+
+			res.map(|_| LockRef::new_range(self, offset, len))
+		}
+	}
+
+
+	fn lock_shared_posix(self) -> impl Future<Output = std::io::Result<Lock<Self>>> + Send {
+		async move {
+			#[cfg(feature = "tokio")]
+			let spawn = tokio::task::spawn_blocking;
+			#[cfg(feature = "async-std")]
+			let spawn = async_std::task::spawn_blocking;
+			#[cfg(feature = "blocking")]
+			let spawn = blocking::unblock;
+
+			let res = spawn(move || {
+				let res = lock_shared_posix(AsDescriptor::as_descriptor(&self));
+				(self, res)
+			}).await;
+
+			#[cfg(feature = "tokio")]
+			let (file, res) = res.unwrap();
+			#[cfg(not(feature = "tokio"))]
+			let (file, res) = res;
+
+			res.map(|_| Lock::new_posix(file))
+		}
+	}
+
+	fn lock_exclusive_posix(self) -> impl Future<Output = std::io::Result<Lock<Self>>> + Send {
 		async move {
 			#[cfg(feature = "tokio")]
 			let spawn = tokio::task::spawn_blocking;
@@ -174,15 +474,60 @@ impl<T: AsDescriptor> AsyncLockFileExt for T {
 			#[cfg(feature = "blocking")]
 			let spawn = blocking::unblock;
 
-			let desc = self.as_descriptor();
+			let res = spawn(move || {
+				let res = lock_exclusive_posix(AsDescriptor::as_descriptor(&self));
+				(self, res)
+			}).await;
+
+			#[cfg(feature = "tokio")]
+			let (file, res) = res.unwrap();
+			#[cfg(not(feature = "tokio"))]
+			let (file, res) = res;
+
+			res.map(|_| Lock::new_posix(file))
+		}
+	}
+
 
-			let res = spawn(move || lock_exclusive(desc))
+	fn lock_shared_posix_ref(&mut self) -> impl Future<Output = std::io::Result<LockRef<Self>>> + Send {
+		async move {
+			#[cfg(feature = "tokio")]
+			let spawn = tokio::task::spawn_blocking;
+			#[cfg(feature = "async-std")]
+			let spawn = async_std::task::spawn_blocking;
+			#[cfg(feature = "blocking")]
+			let spawn = blocking::unblock;
+
+			// SAFETY: see `lock_shared_ref`
+			let raw = as_raw(AsDescriptor::as_descriptor(self));
+			let res = spawn(move || lock_shared_posix(unsafe { from_raw(raw) }))
 				.await;
 
 			#[cfg(feature = "tokio")]
 			let res = res.unwrap();
 
-			res.map(|_| LockRef::new(self))
+			res.map(|_| LockRef::new_posix(self))
+		}
+	}
+
+	fn lock_exclusive_posix_ref(&mut self) -> impl Future<Output = std::io::Result<LockRef<Self>>> + Send {
+		async move {
+			#[cfg(feature = "tokio")]
+			let spawn = tokio::task::spawn_blocking;
+			#[cfg(feature = "async-std")]
+			let spawn = async_std::task::spawn_blocking;
+			#[cfg(feature = "blocking")]
+			let spawn = blocking::unblock;
+
+			// SAFETY: see `lock_shared_ref`
+			let raw = as_raw(AsDescriptor::as_descriptor(self));
+			let res = spawn(move || lock_exclusive_posix(unsafe { from_raw(raw) }))
+				.await;
+
+			#[cfg(feature = "tokio")]
+			let res = res.unwrap();
+
+			res.map(|_| LockRef::new_posix(self))
 		}
 	}
 }
@@ -194,6 +539,160 @@ pub enum LockResult<T: AsDescriptor> {
 	Blocking(T),
 }
 
+/// Tracks which backend a [Lock]/[LockRef] was acquired with, so [unlock](Lock::unlock)
+/// can release it the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum LockSpan {
+	/// Whole-file `flock`/`LockFileEx` lock
+	Whole,
+	/// Byte-range lock covering `offset..offset + len`
+	Range(u64, u64),
+	/// Whole-file POSIX (`fcntl`) lock, see [AsyncLockFileExt::lock_exclusive_posix]
+	Posix,
+}
+
+impl LockSpan {
+	fn unlock(self, desc: Descriptor) -> std::io::Result<()> {
+		match self {
+			Self::Whole => unlock(desc),
+			Self::Range(offset, len) => unlock_range(desc, offset, len),
+			Self::Posix => unlock_posix(desc),
+		}
+	}
+
+	fn lock_shared(self, desc: Descriptor) -> std::io::Result<()> {
+		match self {
+			Self::Whole => lock_shared(desc),
+			Self::Range(offset, len) => lock_shared_range(desc, offset, len),
+			Self::Posix => lock_shared_posix(desc),
+		}
+	}
+
+	fn lock_exclusive(self, desc: Descriptor) -> std::io::Result<()> {
+		match self {
+			Self::Whole => lock_exclusive(desc),
+			Self::Range(offset, len) => lock_exclusive_range(desc, offset, len),
+			Self::Posix => lock_exclusive_posix(desc),
+		}
+	}
+
+	fn try_lock_shared(self, desc: Descriptor) -> std::io::Result<bool> {
+		match self {
+			Self::Whole => try_lock_shared(desc),
+			Self::Range(offset, len) => try_lock_shared_range(desc, offset, len),
+			Self::Posix => try_lock_shared_posix(desc),
+		}
+	}
+
+	fn try_lock_exclusive(self, desc: Descriptor) -> std::io::Result<bool> {
+		match self {
+			Self::Whole => try_lock_exclusive(desc),
+			Self::Range(offset, len) => try_lock_exclusive_range(desc, offset, len),
+			Self::Posix => try_lock_exclusive_posix(desc),
+		}
+	}
+
+	/// Convert a held lock to shared in place.
+	///
+	/// On unix this is just re-issuing `flock`/`fcntl` with the new mode on the same
+	/// descriptor. `fcntl` (range and POSIX locks) converts atomically; `flock` (whole-file
+	/// locks) first removes the old lock and then requests the new one, so a conversion that
+	/// waits or fails can lose the old lock. Windows has no such primitive, so there
+	/// `UnlockFile` is called first, then `LockFileEx` with the new mode -- if the second step
+	/// fails, the lock has already been released.
+	fn downgrade(self, desc: Descriptor) -> std::io::Result<()> {
+		#[cfg(windows)]
+		self.unlock(desc)?;
+
+		self.lock_shared(desc)
+	}
+
+	/// Convert a held lock to exclusive in place, see [downgrade](LockSpan::downgrade).
+	fn upgrade(self, desc: Descriptor) -> std::io::Result<()> {
+		#[cfg(windows)]
+		self.unlock(desc)?;
+
+		self.lock_exclusive(desc)
+	}
+
+	fn try_downgrade(self, desc: Descriptor) -> std::io::Result<bool> {
+		#[cfg(windows)]
+		self.unlock(desc)?;
+
+		self.try_lock_shared(desc)
+	}
+
+	fn try_upgrade(self, desc: Descriptor) -> std::io::Result<bool> {
+		#[cfg(windows)]
+		self.unlock(desc)?;
+
+		self.try_lock_exclusive(desc)
+	}
+}
+
+async fn sleep(d: Duration) {
+	#[cfg(feature = "tokio")]
+	tokio::time::sleep(d).await;
+	#[cfg(feature = "async-std")]
+	async_std::task::sleep(d).await;
+	#[cfg(feature = "blocking")]
+	blocking::unblock(move || std::thread::sleep(d)).await;
+}
+
+/// Cap on the backoff between poll attempts in [lock_poll]/[lock_poll_ref].
+const POLL_BACKOFF_MAX: Duration = Duration::from_millis(50);
+
+async fn lock_poll<T: AsDescriptor>(file: T, span: LockSpan, shared: bool, timeout: Duration) -> std::io::Result<Lock<T>> {
+	let deadline = std::time::Instant::now() + timeout;
+	let mut backoff = Duration::from_millis(1);
+
+	loop {
+		let locked = if shared {
+			span.try_lock_shared(AsDescriptor::as_descriptor(&file))?
+		} else {
+			span.try_lock_exclusive(AsDescriptor::as_descriptor(&file))?
+		};
+
+		if locked {
+			return Ok(Lock { file, span });
+		}
+
+		let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+		if remaining.is_zero() {
+			return Err(std::io::Error::from(std::io::ErrorKind::TimedOut));
+		}
+
+		sleep(backoff.min(remaining)).await;
+		backoff = (backoff * 2).min(POLL_BACKOFF_MAX);
+	}
+}
+
+async fn lock_poll_ref<'a, T: AsDescriptor>(file: &'a mut T, span: LockSpan, shared: bool, timeout: Duration) -> std::io::Result<LockRef<'a, T>> {
+	let deadline = std::time::Instant::now() + timeout;
+	let mut backoff = Duration::from_millis(1);
+
+	loop {
+		let locked = if shared {
+			span.try_lock_shared(AsDescriptor::as_descriptor(file))?
+		} else {
+			span.try_lock_exclusive(AsDescriptor::as_descriptor(file))?
+		};
+
+		if locked {
+			return Ok(LockRef { file, span });
+		}
+
+		let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+		if remaining.is_zero() {
+			return Err(std::io::Error::from(std::io::ErrorKind::TimedOut));
+		}
+
+		sleep(backoff.min(remaining)).await;
+		backoff = (backoff * 2).min(POLL_BACKOFF_MAX);
+	}
+}
+
+
 impl<T: AsDescriptor> LockResult<T> {
 	pub fn unwrap(self) -> Lock<T> {
 		match self {
@@ -209,22 +708,119 @@ impl<T: AsDescriptor> LockResult<T> {
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct LockRef<'a, T: AsDescriptor> {
 	file: &'a mut T,
+	span: LockSpan,
 }
 
 impl<'a, T: AsDescriptor> LockRef<'a, T> {
 	pub(crate) fn new(file: &'a mut T) -> Self {
-		Self { file }
+		Self { file, span: LockSpan::Whole }
+	}
+
+	pub(crate) fn new_range(file: &'a mut T, offset: u64, len: u64) -> Self {
+		Self { file, span: LockSpan::Range(offset, len) }
+	}
+
+	pub(crate) fn new_posix(file: &'a mut T) -> Self {
+		Self { file, span: LockSpan::Posix }
 	}
 
 	/// Unlock the file
 	pub fn unlock(self) -> std::io::Result<()> {
-		unlock(self.file.as_descriptor())?;
+		unsafe { self.unlock_ref() }?;
 		std::mem::forget(self);
 		Ok(())
 	}
 
 	pub unsafe fn unlock_ref(&self) -> std::io::Result<()> {
-		unlock(self.file.as_descriptor())
+		self.span.unlock(AsDescriptor::as_descriptor(self.file))
+	}
+
+	/// Asynchronously convert this lock to exclusive in place, without dropping the guard.
+	///
+	/// Range and POSIX (`fcntl`) locks on unix change mode atomically. Whole-file (`flock`)
+	/// locks on unix, and every lock on windows, are released and reacquired with the new mode,
+	/// so another holder can get in between. On failure the guard is returned alongside the
+	/// error so the caller can recover or retry; the lock is then still held in the old mode
+	/// only for an `fcntl`-backed lock on unix.
+	pub fn upgrade(self) -> impl Future<Output = Result<Self, (Self, std::io::Error)>> + Send where T: Send {
+		async move {
+			#[cfg(feature = "tokio")]
+			let spawn = tokio::task::spawn_blocking;
+			#[cfg(feature = "async-std")]
+			let spawn = async_std::task::spawn_blocking;
+			#[cfg(feature = "blocking")]
+			let spawn = blocking::unblock;
+
+			// SAFETY: see `AsyncLockFileExt::lock_shared_ref`
+			let raw = as_raw(AsDescriptor::as_descriptor(self.file));
+			let span = self.span;
+
+			let res = spawn(move || span.upgrade(unsafe { from_raw(raw) }))
+				.await;
+
+			#[cfg(feature = "tokio")]
+			let res = res.unwrap();
+
+			match res {
+				Ok(()) => Ok(self),
+				Err(e) => Err((self, e)),
+			}
+		}
+	}
+
+	/// Asynchronously convert this lock to shared in place, without dropping the guard.
+	///
+	/// See [upgrade](LockRef::upgrade) for the failure semantics.
+	pub fn downgrade(self) -> impl Future<Output = Result<Self, (Self, std::io::Error)>> + Send where T: Send {
+		async move {
+			#[cfg(feature = "tokio")]
+			let spawn = tokio::task::spawn_blocking;
+			#[cfg(feature = "async-std")]
+			let spawn = async_std::task::spawn_blocking;
+			#[cfg(feature = "blocking")]
+			let spawn = blocking::unblock;
+
+			// SAFETY: see `AsyncLockFileExt::lock_shared_ref`
+			let raw = as_raw(AsDescriptor::as_descriptor(self.file));
+			let span = self.span;
+
+			let res = spawn(move || span.downgrade(unsafe { from_raw(raw) }))
+				.await;
+
+			#[cfg(feature = "tokio")]
+			let res = res.unwrap();
+
+			match res {
+				Ok(()) => Ok(self),
+				Err(e) => Err((self, e)),
+			}
+		}
+	}
+
+	/// Try to convert this lock to exclusive in place, without blocking or dropping the guard.
+	///
+	/// On windows, and for whole-file (`flock`) locks on unix, a failed attempt still loses the
+	/// lock: the old mode is released before the non-blocking re-lock is attempted, and a
+	/// contended re-lock is reported as the same `WouldBlock` error as "never released" would
+	/// be. Treat an `Err` there as "unlocked", not "still held in the old mode". Range and POSIX
+	/// (`fcntl`) locks on unix keep the old mode on failure.
+	pub fn try_upgrade(self) -> Result<Self, (Self, std::io::Error)> {
+		match self.span.try_upgrade(AsDescriptor::as_descriptor(self.file)) {
+			Ok(true) => Ok(self),
+			Ok(false) => Err((self, std::io::Error::from(std::io::ErrorKind::WouldBlock))),
+			Err(e) => Err((self, e)),
+		}
+	}
+
+	/// Try to convert this lock to shared in place, without blocking or dropping the guard.
+	///
+	/// See [try_upgrade](Self::try_upgrade) for when a failed attempt loses the lock.
+	pub fn try_downgrade(self) -> Result<Self, (Self, std::io::Error)> {
+		match self.span.try_downgrade(AsDescriptor::as_descriptor(self.file)) {
+			Ok(true) => Ok(self),
+			Ok(false) => Err((self, std::io::Error::from(std::io::ErrorKind::WouldBlock))),
+			Err(e) => Err((self, e)),
+		}
 	}
 }
 
@@ -256,22 +852,119 @@ impl<'a, T: AsDescriptor> std::ops::DerefMut for LockRef<'a, T> {
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Lock<T: AsDescriptor> {
 	file: T,
+	span: LockSpan,
 }
 
 impl<T: AsDescriptor> Lock<T> {
 	pub(crate) fn new(file: T) -> Self {
-		Self { file }
+		Self { file, span: LockSpan::Whole }
+	}
+
+	pub(crate) fn new_range(file: T, offset: u64, len: u64) -> Self {
+		Self { file, span: LockSpan::Range(offset, len) }
+	}
+
+	pub(crate) fn new_posix(file: T) -> Self {
+		Self { file, span: LockSpan::Posix }
 	}
 
 	/// Unlock the file
 	pub fn unlock(self) -> std::io::Result<()> {
-		unlock(self.file.as_descriptor())?;
+		unsafe { self.unlock_ref() }?;
 		std::mem::forget(self);
 		Ok(())
 	}
 
 	pub unsafe fn unlock_ref(&self) -> std::io::Result<()> {
-		unlock(self.file.as_descriptor())
+		self.span.unlock(AsDescriptor::as_descriptor(&self.file))
+	}
+
+	/// Asynchronously convert this lock to exclusive in place, without dropping the guard.
+	///
+	/// Range and POSIX (`fcntl`) locks on unix change mode atomically. Whole-file (`flock`)
+	/// locks on unix, and every lock on windows, are released and reacquired with the new mode,
+	/// so another holder can get in between. On failure the guard is returned alongside the
+	/// error so the caller can recover or retry; the lock is then still held in the old mode
+	/// only for an `fcntl`-backed lock on unix.
+	pub fn upgrade(self) -> impl Future<Output = Result<Self, (Self, std::io::Error)>> + Send {
+		async move {
+			#[cfg(feature = "tokio")]
+			let spawn = tokio::task::spawn_blocking;
+			#[cfg(feature = "async-std")]
+			let spawn = async_std::task::spawn_blocking;
+			#[cfg(feature = "blocking")]
+			let spawn = blocking::unblock;
+
+			let res = spawn(move || {
+				let res = self.span.upgrade(AsDescriptor::as_descriptor(&self.file));
+				(self, res)
+			}).await;
+
+			#[cfg(feature = "tokio")]
+			let (this, res) = res.unwrap();
+			#[cfg(not(feature = "tokio"))]
+			let (this, res) = res;
+
+			match res {
+				Ok(()) => Ok(this),
+				Err(e) => Err((this, e)),
+			}
+		}
+	}
+
+	/// Asynchronously convert this lock to shared in place, without dropping the guard.
+	///
+	/// See [upgrade](Lock::upgrade) for the failure semantics.
+	pub fn downgrade(self) -> impl Future<Output = Result<Self, (Self, std::io::Error)>> + Send {
+		async move {
+			#[cfg(feature = "tokio")]
+			let spawn = tokio::task::spawn_blocking;
+			#[cfg(feature = "async-std")]
+			let spawn = async_std::task::spawn_blocking;
+			#[cfg(feature = "blocking")]
+			let spawn = blocking::unblock;
+
+			let res = spawn(move || {
+				let res = self.span.downgrade(AsDescriptor::as_descriptor(&self.file));
+				(self, res)
+			}).await;
+
+			#[cfg(feature = "tokio")]
+			let (this, res) = res.unwrap();
+			#[cfg(not(feature = "tokio"))]
+			let (this, res) = res;
+
+			match res {
+				Ok(()) => Ok(this),
+				Err(e) => Err((this, e)),
+			}
+		}
+	}
+
+	/// Try to convert this lock to exclusive in place, without blocking or dropping the guard.
+	///
+	/// On windows, and for whole-file (`flock`) locks on unix, a failed attempt still loses the
+	/// lock: the old mode is released before the non-blocking re-lock is attempted, and a
+	/// contended re-lock is reported as the same `WouldBlock` error as "never released" would
+	/// be. Treat an `Err` there as "unlocked", not "still held in the old mode". Range and POSIX
+	/// (`fcntl`) locks on unix keep the old mode on failure.
+	pub fn try_upgrade(self) -> Result<Self, (Self, std::io::Error)> {
+		match self.span.try_upgrade(AsDescriptor::as_descriptor(&self.file)) {
+			Ok(true) => Ok(self),
+			Ok(false) => Err((self, std::io::Error::from(std::io::ErrorKind::WouldBlock))),
+			Err(e) => Err((self, e)),
+		}
+	}
+
+	/// Try to convert this lock to shared in place, without blocking or dropping the guard.
+	///
+	/// See [try_upgrade](Self::try_upgrade) for when a failed attempt loses the lock.
+	pub fn try_downgrade(self) -> Result<Self, (Self, std::io::Error)> {
+		match self.span.try_downgrade(AsDescriptor::as_descriptor(&self.file)) {
+			Ok(true) => Ok(self),
+			Ok(false) => Err((self, std::io::Error::from(std::io::ErrorKind::WouldBlock))),
+			Err(e) => Err((self, e)),
+		}
 	}
 }
 
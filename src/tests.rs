@@ -56,9 +56,21 @@ fn blocker() -> Process {
 		.unwrap()
 }
 
+fn blocker_posix() -> Process {
+	Process::new("cargo", &[
+			"run",
+			"--example",
+			"block_posix"
+		])
+		.unwrap()
+}
+
 async fn open_file(path: &str) -> std::fs::File {
+	// `.read(true)` is required even though most callers only ever write: a shared (`F_RDLCK`)
+	// `fcntl` range/POSIX lock fails with `EBADF` on a write-only descriptor, unlike `flock`.
 	std::fs::File::options()
 		.create(true)
+		.read(true)
 		.write(true)
 		.open(path)
 		.unwrap()
@@ -150,6 +162,138 @@ async fn test_lock_current_process() {
 }
 
 
+#[cfg_attr(feature = "tokio", tokio::test)]
+#[cfg_attr(feature = "async-std", async_std::test)]
+#[cfg_attr(feature = "blocking", async_std::test)]
+async fn test_lock_range() {
+	let mut file = open_file("target/test3.lock").await;
+	let mut file2 = open_file("target/test3.lock").await;
+
+	// overlapping ranges conflict
+	let lock = file.try_lock_exclusive_range_ref(0, 10)
+		.unwrap()
+		.unwrap();
+	assert!(file2.try_lock_exclusive_range_ref(5, 10).unwrap().is_none());
+	assert!(file2.try_lock_shared_range_ref(5, 10).unwrap().is_none());
+
+	// disjoint ranges don't
+	let lock2 = file2.try_lock_exclusive_range_ref(10, 10)
+		.unwrap()
+		.unwrap();
+
+	lock.unlock().unwrap();
+	lock2.unlock().unwrap();
+}
+
+
+#[cfg_attr(feature = "tokio", tokio::test)]
+#[cfg_attr(feature = "async-std", async_std::test)]
+#[cfg_attr(feature = "blocking", async_std::test)]
+async fn test_lock_posix() {
+	// unlike `flock`/OFD locks, POSIX (`fcntl`) record locks are scoped to the *process*, not
+	// the open file description -- two fds in this same process wouldn't conflict with each
+	// other. Contending against another process, as done here, is the only way to exercise it.
+	let mut blck = blocker_posix();
+	blck.wait_for("ready").unwrap();
+
+	let mut file = open_file("target/test4.lock").await;
+
+	assert!(file.try_lock_exclusive_posix_ref().unwrap().is_none());
+	assert!(file.try_lock_shared_posix_ref().unwrap().is_none());
+
+	blck.kill().unwrap();
+
+	std::thread::sleep(Duration::from_millis(200));
+
+	let lock = file.try_lock_exclusive_posix_ref()
+		.unwrap()
+		.unwrap();
+	lock.unlock().unwrap();
+}
+
+
+#[cfg_attr(feature = "tokio", tokio::test(flavor = "multi_thread", worker_threads = 4))]
+#[cfg_attr(feature = "async-std", async_std::test)]
+#[cfg_attr(feature = "blocking", async_std::test)]
+async fn test_lock_upgrade_downgrade() {
+	let mut file = open_file("target/test5.lock").await;
+	let mut file2 = open_file("target/test5.lock").await;
+
+	// a second shared holder blocks an in-place upgrade
+	let lock = file.try_lock_shared_ref().unwrap().unwrap();
+	let lock2 = file2.try_lock_shared_ref().unwrap().unwrap();
+
+	let lock = lock.try_upgrade().expect_err("upgrade should be blocked by the other reader").0;
+
+	lock2.unlock().unwrap();
+
+	// ... and succeeds once it's released
+	let lock = lock.try_upgrade().unwrap();
+	assert!(file2.try_lock_shared_ref().unwrap().is_none());
+
+	let lock = lock.try_downgrade().unwrap();
+	assert!(file2.try_lock_shared_ref().unwrap().is_some());
+
+	lock.unlock().unwrap();
+
+	#[cfg(feature = "tokio")]
+	os_test_upgrade().await;
+}
+
+// Dropping an in-flight `upgrade()`/`downgrade()` future can't reclaim the guard -- like
+// `lock_exclusive`, the blocking call has already been handed to a pool thread that runs to
+// completion regardless (see `lock_shared_timeout` for the cancellable alternative) -- so the
+// only way to observe it blocking is from the outside, via a background task.
+#[cfg(feature = "tokio")]
+async fn os_test_upgrade() {
+	let file = open_file("target/test6.lock").await;
+	let file2 = open_file("target/test6.lock").await;
+
+	let lock = file.lock_shared().await.unwrap();
+	let lock2 = file2.lock_shared().await.unwrap();
+
+	let task = tokio::spawn(async move {
+		let lock = lock.upgrade().await.unwrap();
+		lock.unlock().unwrap();
+	});
+
+	tokio::time::sleep(Duration::from_millis(200)).await;
+	assert!(!task.is_finished(), "upgrade shouldn't complete while the other reader is held");
+
+	lock2.unlock().unwrap();
+
+	tokio::time::timeout(Duration::from_secs(2), task)
+		.await
+		.expect("upgrade should complete once the other reader is released")
+		.unwrap();
+}
+
+
+#[cfg_attr(feature = "tokio", tokio::test)]
+#[cfg_attr(feature = "async-std", async_std::test)]
+#[cfg_attr(feature = "blocking", async_std::test)]
+async fn test_lock_timeout() {
+	let mut file = open_file("target/test7.lock").await;
+	let mut file2 = open_file("target/test7.lock").await;
+
+	let lock = file.try_lock_exclusive_ref().unwrap().unwrap();
+
+	let before = std::time::Instant::now();
+	let err = file2.lock_exclusive_timeout_ref(Duration::from_millis(200))
+		.await
+		.expect_err("a lock held elsewhere should time out");
+	assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+	assert!(before.elapsed() >= Duration::from_millis(200));
+
+	lock.unlock().unwrap();
+
+	let lock = file2.lock_exclusive_timeout_ref(Duration::from_secs(2))
+		.await
+		.expect("the lock should be free now");
+	lock.unlock().unwrap();
+}
+
+
 #[cfg(feature = "tokio")]
 async fn os_test() {
 	// lock the file
@@ -164,7 +308,10 @@ async fn os_test() {
 
 	assert!(f.try_lock_exclusive_ref().unwrap().is_none());
 
-	let fd = f.as_descriptor();
+	// `Descriptor` is now tied to the lifetime of `f`, so it's no longer possible to
+	// carry one past the `drop(f)` below by accident -- reaching for the raw value
+	// here is the deliberately unsafe escape hatch this test exercises.
+	let fd = as_raw(f.as_descriptor());
 
 	// it seems like the drop call blocks until the lock call is done,
 	// ensure this behavior across platforms
@@ -174,7 +321,9 @@ async fn os_test() {
 	let _waiter = tokio::spawn(async move {
 		s.send(()).unwrap();
 		println!("waiting");
-		tokio::task::spawn_blocking(move || lock_exclusive(fd)).await.unwrap()
+		// SAFETY: not safe -- `f` may be dropped by the time this runs, which is
+		// exactly the EBADF case asserted on below.
+		tokio::task::spawn_blocking(move || lock_exclusive(unsafe { from_raw(fd) })).await.unwrap()
 	});
 
 	let dropper = tokio::spawn(async move {
@@ -200,5 +349,5 @@ async fn os_test() {
 	let err = libc::EBADF;
 	#[cfg(windows)]
 	let err = windows_sys::Win32::Foundation::ERROR_INVALID_HANDLE as i32;
-	assert_eq!(lock_exclusive(fd).map_err(|e| e.raw_os_error()), Err(Some(err)));
+	assert_eq!(lock_exclusive(unsafe { from_raw(fd) }).map_err(|e| e.raw_os_error()), Err(Some(err)));
 }
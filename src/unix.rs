@@ -1,32 +1,65 @@
-use std::{ io::{ Error, ErrorKind }, os::fd::{ AsRawFd, RawFd } };
+use std::{ io::ErrorKind, os::fd::{ AsFd, AsRawFd, BorrowedFd, RawFd } };
 
-use libc::{c_int, LOCK_EX, LOCK_NB, LOCK_SH, LOCK_UN};
+use libc::{c_int, off_t, F_OFD_SETLK, F_OFD_SETLKW, F_RDLCK, F_UNLCK, F_WRLCK};
+use rustix::fs::{flock, FlockOperation};
 
 
-pub type Descriptor = RawFd;
+pub type Descriptor<'a> = BorrowedFd<'a>;
 
 /// Catchall trait for [File](std::fs::File) like types
-pub trait AsDescriptor: Send + 'static {
-	fn as_descriptor(&self) -> Descriptor;
+pub trait AsDescriptor: AsFd + Send + 'static {
+	fn as_descriptor(&self) -> Descriptor<'_>;
 }
 
-impl<T: AsRawFd + Send + 'static> AsDescriptor for T {
-	fn as_descriptor(&self) -> Descriptor {
-		self.as_raw_fd()
+impl<T: AsFd + Send + 'static> AsDescriptor for T {
+	fn as_descriptor(&self) -> Descriptor<'_> {
+		self.as_fd()
 	}
 }
 
 
+// Blocking lock acquisition has to hand a descriptor across the `spawn_blocking`
+// thread boundary, which requires `Send + 'static`. `Descriptor` is intentionally
+// not `'static` (that's what makes it impossible to use past the lifetime of the
+// owning `File`), so for that one crossing it's reduced to the bare `RawFd` and
+// reconstituted on the other side. Whatever calls `from_raw` is responsible for
+// keeping the original descriptor alive for the duration of the borrow.
+pub(crate) type RawDescriptor = RawFd;
+
+pub(crate) fn as_raw(desc: Descriptor<'_>) -> RawDescriptor {
+	desc.as_raw_fd()
+}
+
+/// # Safety
+/// `raw` must refer to an open descriptor that remains valid for the entire lifetime
+/// of the returned [Descriptor].
+pub(crate) unsafe fn from_raw<'a>(raw: RawDescriptor) -> Descriptor<'a> {
+	unsafe { BorrowedFd::borrow_raw(raw) }
+}
+
+
 pub(crate) fn lock_shared(file: Descriptor) -> std::io::Result<()> {
-	lock_file(file, LOCK_SH)
+	lock_file(file, FlockOperation::LockShared)
 }
 
 pub(crate) fn lock_exclusive(file: Descriptor) -> std::io::Result<()> {
-	lock_file(file, LOCK_EX)
+	lock_file(file, FlockOperation::LockExclusive)
 }
 
 pub(crate) fn try_lock_shared(file: Descriptor) -> std::io::Result<bool> {
-	let res = lock_file(file, LOCK_SH | LOCK_NB);
+	try_lock_file(file, FlockOperation::NonBlockingLockShared)
+}
+
+pub(crate) fn try_lock_exclusive(file: Descriptor) -> std::io::Result<bool> {
+	try_lock_file(file, FlockOperation::NonBlockingLockExclusive)
+}
+
+pub(crate) fn unlock(file: Descriptor) -> std::io::Result<()> {
+	lock_file(file, FlockOperation::Unlock)
+}
+
+fn try_lock_file(file: Descriptor, op: FlockOperation) -> std::io::Result<bool> {
+	let res = lock_file(file, op);
 
 	if let Err(e) = &res {
 		if let ErrorKind::WouldBlock = e.kind() {
@@ -37,29 +70,116 @@ pub(crate) fn try_lock_shared(file: Descriptor) -> std::io::Result<bool> {
 	res.map(|_| true)
 }
 
-pub(crate) fn try_lock_exclusive(file: Descriptor) -> std::io::Result<bool> {
-	let res = lock_file(file, LOCK_EX | LOCK_NB);
+fn lock_file(file: Descriptor, op: FlockOperation) -> std::io::Result<()> {
+	flock(file, op).map_err(std::io::Error::from)
+}
+
+
+// `flock` is whole-file only, so byte-range locks are implemented on top of
+// `fcntl`'s open-file-description locks (`F_OFD_SETLK`/`F_OFD_SETLKW`), which
+// lock only the requested region and, like `flock`, are owned by the open
+// file description rather than the process.
+//
+// `fcntl` treats `l_len == 0` as "lock from `l_start` to the largest possible
+// offset", not "lock zero bytes" -- which would silently turn the empty
+// `offset..offset` range into `offset..`. Windows' `LockFileEx`, by contrast, genuinely locks nothing
+// for a zero byte count. Reject `len == 0` up front so `offset..offset + len`
+// means the same thing (and never "nothing") on both platforms.
+
+fn check_range_len(len: u64) -> std::io::Result<()> {
+	match len {
+		0 => Err(std::io::Error::from(std::io::ErrorKind::InvalidInput)),
+		_ => Ok(()),
+	}
+}
+
+pub(crate) fn lock_shared_range(file: Descriptor, offset: u64, len: u64) -> std::io::Result<()> {
+	check_range_len(len)?;
+	fcntl_lock(file, F_RDLCK, offset, len, F_OFD_SETLK, F_OFD_SETLKW, true)
+}
+
+pub(crate) fn lock_exclusive_range(file: Descriptor, offset: u64, len: u64) -> std::io::Result<()> {
+	check_range_len(len)?;
+	fcntl_lock(file, F_WRLCK, offset, len, F_OFD_SETLK, F_OFD_SETLKW, true)
+}
+
+pub(crate) fn try_lock_shared_range(file: Descriptor, offset: u64, len: u64) -> std::io::Result<bool> {
+	check_range_len(len)?;
+	try_fcntl_lock(file, F_RDLCK, offset, len, F_OFD_SETLK, F_OFD_SETLKW)
+}
+
+pub(crate) fn try_lock_exclusive_range(file: Descriptor, offset: u64, len: u64) -> std::io::Result<bool> {
+	check_range_len(len)?;
+	try_fcntl_lock(file, F_WRLCK, offset, len, F_OFD_SETLK, F_OFD_SETLKW)
+}
+
+pub(crate) fn unlock_range(file: Descriptor, offset: u64, len: u64) -> std::io::Result<()> {
+	check_range_len(len)?;
+	fcntl_lock(file, F_UNLCK, offset, len, F_OFD_SETLK, F_OFD_SETLKW, true)
+}
+
+
+// `flock`/OFD locks are per-open-file-description and, being a Linux/BSD-specific
+// extension of the advisory locking model, aren't translated into real network
+// lock requests by the Linux NFS client. POSIX record locks (`fcntl` with the
+// plain, non-`OFD` commands) are: the NFS client turns an `F_SETLK`/`F_SETLKW`
+// into an NLM `LOCK`/`LOCKT` call to the server, giving genuine cross-host mutual
+// exclusion. The tradeoff is that POSIX locks are scoped to the *process*, not
+// the open file description: they vanish when *any* fd referring to the file is
+// closed, and don't nest the way `flock` does. Prefer `*_posix` only on networked
+// filesystems where that tradeoff is worth it.
+
+pub(crate) fn lock_shared_posix(file: Descriptor) -> std::io::Result<()> {
+	fcntl_lock(file, F_RDLCK, 0, 0, libc::F_SETLK, libc::F_SETLKW, true)
+}
+
+pub(crate) fn lock_exclusive_posix(file: Descriptor) -> std::io::Result<()> {
+	fcntl_lock(file, F_WRLCK, 0, 0, libc::F_SETLK, libc::F_SETLKW, true)
+}
+
+pub(crate) fn try_lock_shared_posix(file: Descriptor) -> std::io::Result<bool> {
+	try_fcntl_lock(file, F_RDLCK, 0, 0, libc::F_SETLK, libc::F_SETLKW)
+}
+
+pub(crate) fn try_lock_exclusive_posix(file: Descriptor) -> std::io::Result<bool> {
+	try_fcntl_lock(file, F_WRLCK, 0, 0, libc::F_SETLK, libc::F_SETLKW)
+}
+
+pub(crate) fn unlock_posix(file: Descriptor) -> std::io::Result<()> {
+	fcntl_lock(file, F_UNLCK, 0, 0, libc::F_SETLK, libc::F_SETLKW, true)
+}
+
+
+fn try_fcntl_lock(file: Descriptor, l_type: c_int, offset: u64, len: u64, set_cmd: c_int, setw_cmd: c_int) -> std::io::Result<bool> {
+	let res = fcntl_lock(file, l_type, offset, len, set_cmd, setw_cmd, false);
 
 	if let Err(e) = &res {
-		if let ErrorKind::WouldBlock = e.kind() {
-			return Ok(false);
+		// a held conflicting lock is reported as EAGAIN or EACCES, depending on
+		// the platform, rather than the EWOULDBLOCK that `flock` uses.
+		match e.raw_os_error() {
+			Some(libc::EAGAIN) | Some(libc::EACCES) => return Ok(false),
+			_ => {}
 		}
 	}
 
 	res.map(|_| true)
 }
 
-pub(crate) fn unlock(file: Descriptor) -> std::io::Result<()> {
-	lock_file(file, LOCK_UN)
-}
+fn fcntl_lock(file: Descriptor, l_type: c_int, offset: u64, len: u64, set_cmd: c_int, setw_cmd: c_int, wait: bool) -> std::io::Result<()> {
+	let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+	lock.l_type = l_type as _;
+	lock.l_whence = libc::SEEK_SET as _;
+	lock.l_start = offset as off_t;
+	lock.l_len = len as off_t;
+
+	let cmd = if wait { setw_cmd } else { set_cmd };
 
-fn lock_file(file: Descriptor, op: c_int) -> std::io::Result<()> {
 	let res = unsafe {
-		libc::flock(file, op)
+		libc::fcntl(file.as_raw_fd(), cmd, &mut lock as *mut libc::flock)
 	};
 
 	match res {
 		0 => Ok(()),
-		_ => Err(Error::last_os_error())
+		_ => Err(std::io::Error::last_os_error())
 	}
 }
@@ -1,20 +1,40 @@
-use windows_sys::Win32::{ Foundation::ERROR_LOCK_VIOLATION, Storage::FileSystem::{ LockFileEx, UnlockFile, LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY } };
-use std::os::windows::io::AsRawHandle;
+use windows_sys::Win32::{ Foundation::ERROR_LOCK_VIOLATION, Storage::FileSystem::{ LockFileEx, UnlockFile, LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY }, System::IO::OVERLAPPED };
+use std::os::windows::io::{ AsHandle, AsRawHandle, BorrowedHandle, RawHandle };
 
-pub type Descriptor = isize;
+pub type Descriptor<'a> = BorrowedHandle<'a>;
 
 /// Catchall trait for [File](std::fs::File) like types
-pub trait AsDescriptor: AsRawHandle + Send + 'static {
-	fn as_descriptor(&self) -> Descriptor;
+pub trait AsDescriptor: AsHandle + Send + 'static {
+	fn as_descriptor(&self) -> Descriptor<'_>;
 }
 
-impl<T: AsRawHandle + Send + 'static> AsDescriptor for T {
-	fn as_descriptor(&self) -> Descriptor {
-		self.as_raw_handle() as Descriptor
+impl<T: AsHandle + Send + 'static> AsDescriptor for T {
+	fn as_descriptor(&self) -> Descriptor<'_> {
+		self.as_handle()
 	}
 }
 
 
+// Blocking lock acquisition has to hand a descriptor across the `spawn_blocking`
+// thread boundary, which requires `Send + 'static`. `Descriptor` is intentionally
+// not `'static` (that's what makes it impossible to use past the lifetime of the
+// owning `File`), so for that one crossing it's reduced to the bare `RawHandle`
+// and reconstituted on the other side. Whatever calls `from_raw` is responsible
+// for keeping the original descriptor alive for the duration of the borrow.
+pub(crate) type RawDescriptor = RawHandle;
+
+pub(crate) fn as_raw(desc: Descriptor<'_>) -> RawDescriptor {
+	desc.as_raw_handle()
+}
+
+/// # Safety
+/// `raw` must refer to an open handle that remains valid for the entire lifetime
+/// of the returned [Descriptor].
+pub(crate) unsafe fn from_raw<'a>(raw: RawDescriptor) -> Descriptor<'a> {
+	unsafe { BorrowedHandle::borrow_raw(raw) }
+}
+
+
 pub(crate) fn lock_shared(file: Descriptor) -> std::io::Result<()> {
 	lock_file(file, 0)
 }
@@ -23,35 +43,35 @@ pub(crate) fn lock_exclusive(file: Descriptor) -> std::io::Result<()> {
 	lock_file(file, LOCKFILE_EXCLUSIVE_LOCK)
 }
 
-pub(crate) fn try_lock_shared(file: Descriptor) -> std::io::Result<Option<()>> {
+pub(crate) fn try_lock_shared(file: Descriptor) -> std::io::Result<bool> {
 	let res = lock_file(file, LOCKFILE_FAIL_IMMEDIATELY);
 
 	if let Err(Some(code)) = res.as_ref().map_err(|e| e.raw_os_error()) {
 		if code == ERROR_LOCK_VIOLATION as i32 {
-			return Ok(None);
+			return Ok(false);
 		}
 	}
 
-	res.map(|_| Some(()))
+	res.map(|_| true)
 }
 
-pub(crate) fn try_lock_exclusive(file: Descriptor) -> std::io::Result<Option<()>> {
+pub(crate) fn try_lock_exclusive(file: Descriptor) -> std::io::Result<bool> {
 	let res = lock_file(file, LOCKFILE_FAIL_IMMEDIATELY | LOCKFILE_EXCLUSIVE_LOCK);
 
 	if let Err(Some(code)) = res.as_ref().map_err(|e| e.raw_os_error()) {
 		if code == ERROR_LOCK_VIOLATION as i32 {
-			return Ok(None);
+			return Ok(false);
 		}
 	}
 
-	res.map(|_| Some(()))
+	res.map(|_| true)
 }
 
 fn lock_file(file: Descriptor, flags: u32) -> Result<(), std::io::Error> {
 	let ret = unsafe {
 		let mut overlapped = std::mem::zeroed();
 		LockFileEx(
-			file,
+			file.as_raw_handle() as isize,
 			flags,
 			0,
 			!0,
@@ -69,7 +89,7 @@ fn lock_file(file: Descriptor, flags: u32) -> Result<(), std::io::Error> {
 pub(crate) fn unlock(file: Descriptor) -> std::io::Result<()> {
 	let ret = unsafe {
 		UnlockFile(
-			file,
+			file.as_raw_handle() as isize,
 			0,
 			0,
 			!0,
@@ -82,3 +102,115 @@ pub(crate) fn unlock(file: Descriptor) -> std::io::Result<()> {
 		_ => Ok(())
 	}
 }
+
+
+// Windows only has one advisory locking mechanism (`LockFileEx`), and it already
+// works correctly over SMB/CIFS network shares, so the `_posix` variants are
+// just an alias -- they exist so cross-platform callers can pick the NFS-safe
+// backend on unix without needing a `cfg` of their own.
+
+pub(crate) fn lock_shared_posix(file: Descriptor) -> std::io::Result<()> {
+	lock_shared(file)
+}
+
+pub(crate) fn lock_exclusive_posix(file: Descriptor) -> std::io::Result<()> {
+	lock_exclusive(file)
+}
+
+pub(crate) fn try_lock_shared_posix(file: Descriptor) -> std::io::Result<bool> {
+	try_lock_shared(file)
+}
+
+pub(crate) fn try_lock_exclusive_posix(file: Descriptor) -> std::io::Result<bool> {
+	try_lock_exclusive(file)
+}
+
+pub(crate) fn unlock_posix(file: Descriptor) -> std::io::Result<()> {
+	unlock(file)
+}
+
+
+pub(crate) fn lock_shared_range(file: Descriptor, offset: u64, len: u64) -> std::io::Result<()> {
+	lock_file_range(file, 0, offset, len)
+}
+
+pub(crate) fn lock_exclusive_range(file: Descriptor, offset: u64, len: u64) -> std::io::Result<()> {
+	lock_file_range(file, LOCKFILE_EXCLUSIVE_LOCK, offset, len)
+}
+
+pub(crate) fn try_lock_shared_range(file: Descriptor, offset: u64, len: u64) -> std::io::Result<bool> {
+	let res = lock_file_range(file, LOCKFILE_FAIL_IMMEDIATELY, offset, len);
+
+	if let Err(Some(code)) = res.as_ref().map_err(|e| e.raw_os_error()) {
+		if code == ERROR_LOCK_VIOLATION as i32 {
+			return Ok(false);
+		}
+	}
+
+	res.map(|_| true)
+}
+
+pub(crate) fn try_lock_exclusive_range(file: Descriptor, offset: u64, len: u64) -> std::io::Result<bool> {
+	let res = lock_file_range(file, LOCKFILE_FAIL_IMMEDIATELY | LOCKFILE_EXCLUSIVE_LOCK, offset, len);
+
+	if let Err(Some(code)) = res.as_ref().map_err(|e| e.raw_os_error()) {
+		if code == ERROR_LOCK_VIOLATION as i32 {
+			return Ok(false);
+		}
+	}
+
+	res.map(|_| true)
+}
+
+// `LockFileEx`, unlike POSIX `fcntl`, genuinely locks zero bytes for a zero byte
+// count rather than extending the range to EOF. Reject it anyway so `len == 0`
+// means the same thing (an error, not "nothing") as it does on unix.
+fn check_range_len(len: u64) -> std::io::Result<()> {
+	match len {
+		0 => Err(std::io::Error::from(std::io::ErrorKind::InvalidInput)),
+		_ => Ok(()),
+	}
+}
+
+fn lock_file_range(file: Descriptor, flags: u32, offset: u64, len: u64) -> Result<(), std::io::Error> {
+	check_range_len(len)?;
+
+	let ret = unsafe {
+		let mut overlapped: OVERLAPPED = std::mem::zeroed();
+		overlapped.Anonymous.Anonymous.Offset = offset as u32;
+		overlapped.Anonymous.Anonymous.OffsetHigh = (offset >> 32) as u32;
+
+		LockFileEx(
+			file.as_raw_handle() as isize,
+			flags,
+			0,
+			len as u32,
+			(len >> 32) as u32,
+			&mut overlapped,
+		)
+	};
+
+	match ret {
+		0 => Err(std::io::Error::last_os_error()),
+		_ => Ok(())
+	}
+}
+
+pub(crate) fn unlock_range(file: Descriptor, offset: u64, len: u64) -> std::io::Result<()> {
+	check_range_len(len)?;
+
+	let ret = unsafe {
+		UnlockFile(
+			file.as_raw_handle() as isize,
+			offset as u32,
+			(offset >> 32) as u32,
+			len as u32,
+			(len >> 32) as u32
+		)
+	};
+
+	match ret {
+		0 => Err(std::io::Error::last_os_error()),
+		_ => Ok(())
+	}
+}